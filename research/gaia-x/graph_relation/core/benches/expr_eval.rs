@@ -0,0 +1,58 @@
+//
+//! Copyright 2020 Alibaba Group Holding Limited.
+//!
+//! Licensed under the Apache License, Version 2.0 (the "License");
+//! you may not use this file except in compliance with the License.
+//! You may obtain a copy of the License at
+//!
+//! http://www.apache.org/licenses/LICENSE-2.0
+//!
+//! Unless required by applicable law or agreed to in writing, software
+//! distributed under the License is distributed on an "AS IS" BASIS,
+//! WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//! See the License for the specific language governing permissions and
+//! limitations under the License.
+//!
+
+//! Benchmarks the interpreted `Evaluator::eval` against the compiled `CompiledExpr::eval`
+//! over 1M evaluations each. The expression is made of constants only and is evaluated
+//! with no context (`None`), so `CompiledExpr` folds it to a single constant; no `Var`
+//! lookup is measured.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use graph_relation_core::expr::eval::{Evaluator, NoneContext};
+use graph_relation_core::expr::to_suffix_expr_pb;
+use graph_relation_core::expr::token::tokenize;
+use graph_relation_core::FromPb;
+
+const CONTEXT_STREAM_LEN: usize = 1_000_000;
+
+fn build_evaluator<'a>(expr: &str) -> Evaluator<'a> {
+    Evaluator::from_pb(to_suffix_expr_pb(tokenize(expr).unwrap()).unwrap()).unwrap()
+}
+
+fn bench_interpreted(c: &mut Criterion) {
+    let eval = build_evaluator("((1 + 2) * 3) / 7 * 8 + 12.5 / 10.1 == 2 ^ 10 > 10");
+    c.bench_function("interpreted eval, 1M evaluations", |b| {
+        b.iter(|| {
+            for _ in 0..CONTEXT_STREAM_LEN {
+                black_box(eval.eval::<(), NoneContext>(None).unwrap());
+            }
+        })
+    });
+}
+
+fn bench_compiled(c: &mut Criterion) {
+    let eval = build_evaluator("((1 + 2) * 3) / 7 * 8 + 12.5 / 10.1 == 2 ^ 10 > 10");
+    let compiled = eval.compile();
+    c.bench_function("compiled eval, 1M evaluations", |b| {
+        b.iter(|| {
+            for _ in 0..CONTEXT_STREAM_LEN {
+                black_box(compiled.eval::<(), NoneContext>(None).unwrap());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_interpreted, bench_compiled);
+criterion_main!(benches);
@@ -23,8 +23,11 @@ use crate::graph::element::Element;
 use crate::graph::property::{Details, PropKey};
 use crate::{FromPb, NameOrId};
 use dyn_type::arith::Exp;
-use dyn_type::{BorrowObject, Object};
+use dyn_type::{BorrowObject, Object, Primitives};
+use lazy_static::lazy_static;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::RwLock;
 
 pub struct Evaluator<'a> {
     /// A suffix-tree-based expression for evaluating
@@ -32,6 +35,10 @@ pub struct Evaluator<'a> {
     /// A stack for evaluating the suffix-tree-based expression
     /// Wrap it in a `RefCell` to avoid conflict mutable reference
     stack: RefCell<Vec<BorrowObject<'a>>>,
+    /// Owns the `Object`s freshly allocated by built-in function calls (e.g. `toLower`),
+    /// so their results can be borrowed from with the same lifetime as everything else
+    /// on `stack` instead of being cloned on every use.
+    fn_arena: ObjectArena,
 }
 
 /// An inner representation of `pb::ExprUnit` for one-shot translation of `pb::ExprUnit`.
@@ -43,6 +50,45 @@ enum InnerOpr {
         tag: NameOrId,
         prop_key: Option<PropKey>,
     },
+    // Not produced by `FromPb` below: `expr_unit::Item` has no `Function` variant yet, so
+    // this is only ever reachable by constructing an `InnerOpr` directly. Wiring a real
+    // `Item::Function` through `FromPb` needs a schema change outside this checkout.
+    Function {
+        name: String,
+        arity: usize,
+    },
+}
+
+/// A bump arena of boxed `Object`s, used to give the result of a built-in function call
+/// (which, unlike a `Var`/`Const` operand, is freshly allocated rather than borrowed from
+/// the context or the suffix-tree) a `BorrowObject` with the same lifetime as the owning
+/// `Evaluator`/`CompiledExpr`. Boxing keeps each `Object`'s address stable as the arena's
+/// backing `Vec` grows.
+#[derive(Default)]
+struct ObjectArena {
+    boxed: RefCell<Vec<Box<Object>>>,
+}
+
+impl ObjectArena {
+    /// Drop every `Object` this arena has allocated, invalidating any `BorrowObject`
+    /// previously handed out by `alloc`. Only ever called from places where that's
+    /// provably safe: `Evaluator::eval`/`CompiledExpr::eval` clear it at the top of their
+    /// own call, right after clearing their `stack` of anything a previous call left there
+    /// (every call returns only an owned `Object`); `Evaluator::reset` takes `&mut self` specifically so the
+    /// borrow checker rules out calling it while any borrow from this arena is still held.
+    fn clear(&self) {
+        self.boxed.borrow_mut().clear();
+    }
+
+    /// Move `obj` into the arena and return a `BorrowObject` borrowing from its stable,
+    /// boxed location. See `clear` for why this can't outlive a subsequent `clear` call.
+    fn alloc<'a>(&'a self, obj: Object) -> BorrowObject<'a> {
+        let mut boxed = self.boxed.borrow_mut();
+        boxed.push(Box::new(obj));
+        let ptr: *const Object = boxed.last().unwrap().as_ref();
+        drop(boxed);
+        unsafe { (&*ptr).as_borrow() }
+    }
 }
 
 /// A `Context` gives the behavior of obtaining a certain tag from the runtime
@@ -69,10 +115,107 @@ impl<'a> FromPb<Vec<pb::ExprUnit>> for Evaluator<'a> {
         Ok(Self {
             suffix_tree: inner_tree,
             stack: RefCell::new(vec![]),
+            fn_arena: ObjectArena::default(),
         })
     }
 }
 
+/// A built-in function handler, registered by name in `BUILTIN_FUNCTIONS`. Receives the
+/// already-evaluated arguments in call order and returns an owned `Object`, since most
+/// built-ins (e.g. `toLower`) allocate a fresh result rather than borrowing one.
+pub type BuiltinFn = fn(&[BorrowObject]) -> ExprResult<Object>;
+
+lazy_static! {
+    static ref BUILTIN_FUNCTIONS: RwLock<HashMap<String, BuiltinFn>> =
+        RwLock::new(default_builtin_functions());
+}
+
+fn default_builtin_functions() -> HashMap<String, BuiltinFn> {
+    let mut functions: HashMap<String, BuiltinFn> = HashMap::new();
+    functions.insert("length".to_string(), builtin_length as BuiltinFn);
+    functions.insert("contains".to_string(), builtin_contains as BuiltinFn);
+    functions.insert("startsWith".to_string(), builtin_starts_with as BuiltinFn);
+    functions.insert("toLower".to_string(), builtin_to_lower as BuiltinFn);
+    functions.insert("abs".to_string(), builtin_abs as BuiltinFn);
+    functions.insert("floor".to_string(), builtin_floor as BuiltinFn);
+    functions.insert("power".to_string(), builtin_power as BuiltinFn);
+    functions
+}
+
+/// Register (or override) a built-in function for use in expressions. Downstream crates
+/// must call this before constructing any `Evaluator` that invokes the function by name.
+pub fn register_function(name: &str, f: BuiltinFn) {
+    BUILTIN_FUNCTIONS.write().unwrap().insert(name.to_string(), f);
+}
+
+fn apply_function(name: &str, args: &[BorrowObject]) -> ExprResult<Object> {
+    let registry = BUILTIN_FUNCTIONS.read().unwrap();
+    let f = registry
+        .get(name)
+        .ok_or_else(|| ExprError::from(format!("undefined function `{}`", name).as_str()))?;
+    f(args)
+}
+
+fn expect_arity(name: &str, args: &[BorrowObject], arity: usize) -> ExprResult<()> {
+    if args.len() != arity {
+        Err(ExprError::from(
+            format!("`{}` expects {} argument(s), got {}", name, arity, args.len()).as_str(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn primitive_as_f64(p: Primitives) -> f64 {
+    match p {
+        Primitives::Byte(v) => v as f64,
+        Primitives::Integer(v) => v as f64,
+        Primitives::Long(v) => v as f64,
+        Primitives::ULLong(v) => v as f64,
+        Primitives::Float(v) => v,
+    }
+}
+
+fn builtin_length(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("length", args, 1)?;
+    Ok(Object::from(args[0].as_str()?.chars().count() as i64))
+}
+
+fn builtin_contains(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("contains", args, 2)?;
+    Ok(Object::from(args[0].as_str()?.contains(args[1].as_str()?)))
+}
+
+fn builtin_starts_with(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("startsWith", args, 2)?;
+    Ok(Object::from(args[0].as_str()?.starts_with(args[1].as_str()?)))
+}
+
+fn builtin_to_lower(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("toLower", args, 1)?;
+    Ok(Object::from(args[0].as_str()?.to_lowercase()))
+}
+
+fn builtin_abs(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("abs", args, 1)?;
+    Ok(match args[0].as_primitive()? {
+        Primitives::Float(f) => Object::from(f.abs()),
+        other => Object::from(primitive_as_f64(other).abs() as i64),
+    })
+}
+
+fn builtin_floor(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("floor", args, 1)?;
+    Ok(Object::from(primitive_as_f64(args[0].as_primitive()?).floor()))
+}
+
+fn builtin_power(args: &[BorrowObject]) -> ExprResult<Object> {
+    expect_arity("power", args, 2)?;
+    let base = primitive_as_f64(args[0].as_primitive()?);
+    let exponent = primitive_as_f64(args[1].as_primitive()?);
+    Ok(Object::from(base.powf(exponent)))
+}
+
 fn apply_arith<'a>(
     arith: &pb::Arithmetic,
     first: Option<BorrowObject<'a>>,
@@ -107,6 +250,9 @@ fn apply_logical<'a>(
         if first.is_some() && second.is_some() {
             let a = first.unwrap();
             let b = second.unwrap();
+            if logical == &Logical::Within || logical == &Logical::Without {
+                return apply_within(a, b, logical == &Logical::Within);
+            }
             let rst = match logical {
                 Logical::Eq => (a == b).into(),
                 Logical::Ne => (a != b).into(),
@@ -116,9 +262,7 @@ fn apply_logical<'a>(
                 Logical::Ge => (a >= b).into(),
                 Logical::And => (a.as_bool()? && b.as_bool()?).into(),
                 Logical::Or => (a.as_bool()? || b.as_bool()?).into(),
-                Logical::Not => unreachable!(),
-                // todo within, without
-                _ => unimplemented!(),
+                Logical::Not | Logical::Within | Logical::Without => unreachable!(),
             };
             return Ok(rst);
         }
@@ -127,6 +271,25 @@ fn apply_logical<'a>(
     Err(ExprError::MissingOperands)
 }
 
+/// Evaluate `a within b` (or its negation `a without b`), where `b` is expected to be
+/// a collection `Object`. The scalar `a` is compared against each element of the
+/// collection via the same `PartialEq` path used by `Logical::Eq`.
+fn apply_within<'a>(
+    a: BorrowObject<'a>,
+    b: BorrowObject<'a>,
+    is_within: bool,
+) -> ExprResult<BorrowObject<'a>> {
+    match b {
+        BorrowObject::Vector(collection) => {
+            let contains = collection.iter().any(|item| *item == a);
+            Ok((contains == is_within).into())
+        }
+        _ => Err(ExprError::from(
+            "`within`/`without` expect a collection as the right-hand operand",
+        )),
+    }
+}
+
 // Private api
 impl<'a> Evaluator<'a> {
     /// Evaluate simple expression that contains less than three operators
@@ -144,7 +307,8 @@ impl<'a> Evaluator<'a> {
                 .ok_or(ExprError::NoneOperand)?
                 .into());
         } else if self.suffix_tree.len() == 2 {
-            // must be not
+            // a unary logical `!` or a one-argument function call; an `Arith` with a
+            // single operand fails with `MissingOperands`
             if let InnerOpr::Logical(logical) = &self.suffix_tree[1] {
                 return Ok(apply_logical(
                     logical,
@@ -152,6 +316,20 @@ impl<'a> Evaluator<'a> {
                     None,
                 )?
                 .into());
+            } else if let InnerOpr::Arith(arith) = &self.suffix_tree[1] {
+                return Ok(apply_arith(
+                    arith,
+                    self.suffix_tree[0].eval_as_borrow_object(context)?,
+                    None,
+                )?
+                .into());
+            } else if let InnerOpr::Function { name, arity } = &self.suffix_tree[1] {
+                if *arity == 1 {
+                    let arg = self.suffix_tree[0]
+                        .eval_as_borrow_object(context)?
+                        .ok_or(ExprError::NoneOperand)?;
+                    return apply_function(name, &[arg]);
+                }
             }
         } else {
             if let InnerOpr::Logical(logical) = &self.suffix_tree[2] {
@@ -168,6 +346,16 @@ impl<'a> Evaluator<'a> {
                     self.suffix_tree[1].eval_as_borrow_object(context)?,
                 )?
                 .into());
+            } else if let InnerOpr::Function { name, arity } = &self.suffix_tree[2] {
+                if *arity == 2 {
+                    let a = self.suffix_tree[0]
+                        .eval_as_borrow_object(context)?
+                        .ok_or(ExprError::NoneOperand)?;
+                    let b = self.suffix_tree[1]
+                        .eval_as_borrow_object(context)?
+                        .ok_or(ExprError::NoneOperand)?;
+                    return apply_function(name, &[a, b]);
+                }
             }
         }
 
@@ -176,9 +364,26 @@ impl<'a> Evaluator<'a> {
 }
 
 impl<'a> Evaluator<'a> {
-    /// Reset the status of the evaluator for further evaluation
-    pub fn reset(&self) {
+    /// Reset the status of the evaluator for further evaluation.
+    ///
+    /// Takes `&mut self` (rather than `&self`, like the rest of this impl) so the borrow
+    /// checker can prove this is safe: `fn_arena.clear()` drops the boxed `Object`s backing
+    /// any `BorrowObject` previously handed out by a function call, so this can only run
+    /// once every such borrow — which ties up a shared borrow of `self` — has gone out of
+    /// scope. A `&self` signature here would let a caller still holding one of those
+    /// borrows call `reset` anyway, dangling it.
+    pub fn reset(&mut self) {
         self.stack.borrow_mut().clear();
+        self.fn_arena.clear();
+    }
+
+    /// Lower the suffix-tree into a flat, pre-typed `CompiledExpr`, folding any
+    /// const-only subtree at compile time and pre-binding each `Var` to its tag/
+    /// property lookup. Use this for the hot path of evaluating the same expression
+    /// over a large stream of contexts (e.g. filtering vertices/edges), where
+    /// `eval` would otherwise re-walk and re-type the suffix-tree on every call.
+    pub fn compile(&self) -> CompiledExpr<'a> {
+        CompiledExpr::from_suffix_tree(&self.suffix_tree)
     }
 
     /// Evaluate an expression with an optional context.
@@ -191,6 +396,11 @@ impl<'a> Evaluator<'a> {
             return self.eval_without_stack(context);
         }
         stack.clear();
+        // `stack.clear()` above drops whatever an earlier (possibly failed) call left on it,
+        // and every call returns only an owned `Object` (see the `.into()` below), so no
+        // `BorrowObject` backed by `fn_arena` from a previous call can still be alive here —
+        // clearing it now is safe and bounds its growth across calls.
+        self.fn_arena.clear();
         for opr in &self.suffix_tree {
             if opr.is_operand() {
                 if let Some(obj) = opr.eval_as_borrow_object(context)? {
@@ -198,6 +408,14 @@ impl<'a> Evaluator<'a> {
                 } else {
                     return Err(ExprError::NoneOperand);
                 }
+            } else if let InnerOpr::Function { name, arity } = opr {
+                let mut args: Vec<BorrowObject> = Vec::with_capacity(*arity);
+                for _ in 0..*arity {
+                    args.push(stack.pop().ok_or(ExprError::MissingOperands)?);
+                }
+                args.reverse();
+                let result = apply_function(name, &args)?;
+                stack.push(self.fn_arena.alloc(result));
             } else {
                 let first = stack.pop();
                 match opr {
@@ -226,6 +444,219 @@ impl<'a> Evaluator<'a> {
     }
 }
 
+/// One instruction of a `CompiledExpr` program. Unlike `InnerOpr`, a `Logical`/`Arith`/
+/// `Function` instruction is only emitted when at least one of its operands is not known
+/// at compile time, or when folding its known operands fails; in the latter case the
+/// error is raised when the `CompiledExpr` is evaluated.
+enum Instruction {
+    Const(Option<Object>),
+    Var {
+        tag: NameOrId,
+        prop_key: Option<PropKey>,
+    },
+    Logical(pb::Logical),
+    Arith(pb::Arithmetic),
+    Function { name: String, arity: usize },
+}
+
+impl Instruction {
+    fn is_operand(&self) -> bool {
+        matches!(self, Instruction::Const(_) | Instruction::Var { .. })
+    }
+}
+
+/// The compile-time value of a suffix-tree subtree: either folded down to a concrete
+/// `Object`, or the (already correctly ordered) fragment of instructions needed to
+/// produce it at `eval` time.
+enum Slot {
+    Known(Object),
+    Dynamic(Vec<Instruction>),
+}
+
+impl Slot {
+    /// Turn this slot into its instruction fragment, materializing a `Known` value
+    /// into a single `Const` instruction.
+    fn into_fragment(self) -> Vec<Instruction> {
+        match self {
+            Slot::Known(obj) => vec![Instruction::Const(Some(obj))],
+            Slot::Dynamic(frag) => frag,
+        }
+    }
+}
+
+/// A flattened, pre-typed program lowered from a `suffix_tree`, produced by
+/// `Evaluator::compile`. Constant subtrees are folded away, `Var` lookups are
+/// pre-bound, and evaluation reuses a single pre-sized stack buffer across calls.
+pub struct CompiledExpr<'a> {
+    program: Vec<Instruction>,
+    stack: RefCell<Vec<BorrowObject<'a>>>,
+    fn_arena: ObjectArena,
+}
+
+impl<'a> CompiledExpr<'a> {
+    /// Build `shadow`, a stack of `Slot`s mirroring the suffix-tree's own evaluation
+    /// stack, where each `Slot` is either a folded `Object` or a self-contained,
+    /// correctly-ordered instruction fragment for the subtree it represents. Building
+    /// fragments bottom-up (rather than appending to one flat `Vec` as we scan) keeps
+    /// left-to-right operand order correct even when, say, the left operand folds to
+    /// a constant but the right one doesn't.
+    fn from_suffix_tree(suffix_tree: &[InnerOpr]) -> Self {
+        let mut shadow: Vec<Slot> = Vec::with_capacity(suffix_tree.len());
+
+        for opr in suffix_tree {
+            match opr {
+                InnerOpr::Const(Some(obj)) => shadow.push(Slot::Known(obj.clone())),
+                InnerOpr::Const(None) => {
+                    shadow.push(Slot::Dynamic(vec![Instruction::Const(None)]))
+                }
+                InnerOpr::Var { tag, prop_key } => shadow.push(Slot::Dynamic(vec![Instruction::Var {
+                    tag: tag.clone(),
+                    prop_key: prop_key.clone(),
+                }])),
+                InnerOpr::Logical(logical) if logical == &Logical::Not => {
+                    let a = shadow.pop().unwrap();
+                    if let Slot::Known(obj) = &a {
+                        if let Ok(folded) = apply_logical(logical, Some(obj.as_borrow()), None) {
+                            shadow.push(Slot::Known(folded.into()));
+                            continue;
+                        }
+                    }
+                    let mut frag = a.into_fragment();
+                    frag.push(Instruction::Logical(logical.clone()));
+                    shadow.push(Slot::Dynamic(frag));
+                }
+                InnerOpr::Logical(logical) => {
+                    let b = shadow.pop().unwrap();
+                    let a = shadow.pop().unwrap();
+                    if let (Slot::Known(a_obj), Slot::Known(b_obj)) = (&a, &b) {
+                        if let Ok(folded) =
+                            apply_logical(logical, Some(a_obj.as_borrow()), Some(b_obj.as_borrow()))
+                        {
+                            shadow.push(Slot::Known(folded.into()));
+                            continue;
+                        }
+                    }
+                    let mut frag = a.into_fragment();
+                    frag.extend(b.into_fragment());
+                    frag.push(Instruction::Logical(logical.clone()));
+                    shadow.push(Slot::Dynamic(frag));
+                }
+                InnerOpr::Arith(arith) => {
+                    let b = shadow.pop().unwrap();
+                    let a = shadow.pop().unwrap();
+                    if let (Slot::Known(a_obj), Slot::Known(b_obj)) = (&a, &b) {
+                        if let Ok(folded) =
+                            apply_arith(arith, Some(a_obj.as_borrow()), Some(b_obj.as_borrow()))
+                        {
+                            shadow.push(Slot::Known(folded.into()));
+                            continue;
+                        }
+                    }
+                    let mut frag = a.into_fragment();
+                    frag.extend(b.into_fragment());
+                    frag.push(Instruction::Arith(arith.clone()));
+                    shadow.push(Slot::Dynamic(frag));
+                }
+                InnerOpr::Function { name, arity } => {
+                    let args: Vec<Slot> = (0..*arity).map(|_| shadow.pop().unwrap()).rev().collect();
+                    if let Some(known_args) = args
+                        .iter()
+                        .map(|slot| match slot {
+                            Slot::Known(obj) => Some(obj.as_borrow()),
+                            Slot::Dynamic(_) => None,
+                        })
+                        .collect::<Option<Vec<BorrowObject>>>()
+                    {
+                        if let Ok(folded) = apply_function(name, &known_args) {
+                            shadow.push(Slot::Known(folded));
+                            continue;
+                        }
+                    }
+                    let mut frag = Vec::new();
+                    for arg in args {
+                        frag.extend(arg.into_fragment());
+                    }
+                    frag.push(Instruction::Function {
+                        name: name.clone(),
+                        arity: *arity,
+                    });
+                    shadow.push(Slot::Dynamic(frag));
+                }
+            }
+        }
+
+        let program = shadow.pop().map(Slot::into_fragment).unwrap_or_default();
+        let capacity = program.len();
+        CompiledExpr {
+            program,
+            stack: RefCell::new(Vec::with_capacity(capacity)),
+            fn_arena: ObjectArena::default(),
+        }
+    }
+
+    /// Evaluate the compiled program with an optional context, reusing the
+    /// pre-sized stack buffer across calls.
+    pub fn eval<E: Element + 'a, C: Context<E> + 'a>(
+        &'a self,
+        context: Option<&'a C>,
+    ) -> ExprResult<Object> {
+        let mut stack = self.stack.borrow_mut();
+        stack.clear();
+        // Safe for the same reason as in `Evaluator::eval`: `stack` was just cleared and
+        // every call returns only an owned `Object`, so no borrow from a previous call survives.
+        self.fn_arena.clear();
+
+        if self.program.is_empty() {
+            return Err(ExprError::EmptyExpression);
+        }
+
+        for instr in &self.program {
+            if instr.is_operand() {
+                let obj = match instr {
+                    Instruction::Const(Some(c)) => Some(c.as_borrow()),
+                    Instruction::Const(None) => None,
+                    Instruction::Var { tag, prop_key } => {
+                        eval_var_as_borrow_object(tag, prop_key, context)?
+                    }
+                    _ => unreachable!(),
+                };
+                stack.push(obj.ok_or(ExprError::NoneOperand)?);
+            } else if let Instruction::Function { name, arity } = instr {
+                let mut args: Vec<BorrowObject> = Vec::with_capacity(*arity);
+                for _ in 0..*arity {
+                    args.push(stack.pop().ok_or(ExprError::MissingOperands)?);
+                }
+                args.reverse();
+                let result = apply_function(name, &args)?;
+                stack.push(self.fn_arena.alloc(result));
+            } else {
+                let first = stack.pop();
+                match instr {
+                    Instruction::Logical(logical) => {
+                        let rst = if logical == &Logical::Not {
+                            apply_logical(logical, first, None)?
+                        } else {
+                            apply_logical(logical, stack.pop(), first)?
+                        };
+                        stack.push(rst);
+                    }
+                    Instruction::Arith(arith) => {
+                        let rst = apply_arith(arith, stack.pop(), first)?;
+                        stack.push(rst);
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        if stack.len() == 1 {
+            Ok(stack.pop().unwrap().into())
+        } else {
+            Err("invalid expression".into())
+        }
+    }
+}
+
 impl FromPb<pb::ExprUnit> for InnerOpr {
     fn from_pb(unit: ExprUnit) -> ParsePbResult<Self>
     where
@@ -262,6 +693,32 @@ impl FromPb<pb::ExprUnit> for InnerOpr {
     }
 }
 
+/// Resolve a `Var { tag, prop_key }` operand against the given context, shared by
+/// `InnerOpr::eval_as_borrow_object` and `CompiledExpr::eval`'s pre-bound `Instruction::Var`.
+fn eval_var_as_borrow_object<'a, E: Element + 'a, C: Context<E> + 'a>(
+    tag: &NameOrId,
+    prop_key: &Option<PropKey>,
+    context: Option<&'a C>,
+) -> ExprResult<Option<BorrowObject<'a>>> {
+    if let Some(ctxt) = context {
+        if let Some(property) = prop_key {
+            if let Some(element) = ctxt.get(tag) {
+                if let Some(details) = element.details() {
+                    return Ok(details.get(property));
+                }
+            }
+        } else {
+            if let Some(field) = ctxt.get(tag) {
+                return Ok(Some(field.as_borrow_object()));
+            }
+        }
+    }
+
+    Err(ExprError::MissingContext(
+        "missing context for evaluating variables".into(),
+    ))
+}
+
 impl InnerOpr {
     pub fn eval_as_borrow_object<'a, E: Element + 'a, C: Context<E> + 'a>(
         &'a self,
@@ -273,26 +730,7 @@ impl InnerOpr {
             } else {
                 None
             }),
-            Self::Var { tag, prop_key } => {
-                if context.is_some() {
-                    let ctxt = context.unwrap();
-                    if let Some(property) = prop_key {
-                        if let Some(element) = ctxt.get(tag) {
-                            if let Some(details) = element.details() {
-                                return Ok(details.get(property));
-                            }
-                        }
-                    } else {
-                        if let Some(field) = ctxt.get(tag) {
-                            return Ok(Some(field.as_borrow_object()));
-                        }
-                    }
-                }
-
-                Err(ExprError::MissingContext(
-                    "missing context for evaluating variables".into(),
-                ))
-            }
+            Self::Var { tag, prop_key } => eval_var_as_borrow_object(tag, prop_key, context),
             _ => Ok(None),
         }
     }
@@ -318,9 +756,18 @@ impl pb::Const {
                     Str(s) => Ok(Some(s.clone().into())),
                     Blob(blob) => Ok(Some(blob.clone().into())),
                     None(_) => Ok(Option::None),
-                    I32Array(_) | I64Array(_) | F64Array(_) | StrArray(_) => {
-                        Err(ParsePbError::from("the const values of `I32Array`, `I64Array`, `F64Array`, `StrArray` are unsupported"))
+                    I32Array(arr) => {
+                        Ok(Some(Object::Vector(arr.item.iter().map(|i| (*i).into()).collect())))
+                    }
+                    I64Array(arr) => {
+                        Ok(Some(Object::Vector(arr.item.iter().map(|i| (*i).into()).collect())))
                     }
+                    F64Array(arr) => {
+                        Ok(Some(Object::Vector(arr.item.iter().map(|f| (*f).into()).collect())))
+                    }
+                    StrArray(arr) => Ok(Some(Object::Vector(
+                        arr.item.iter().cloned().map(Object::from).collect(),
+                    ))),
                 };
             }
         }
@@ -329,12 +776,41 @@ impl pb::Const {
     }
 }
 
+// NOTE: `expr::token`/`expr::mod` (the tokenizer and suffix-tree builder) are not part of
+// this checkout, so the tests below are written against the `Evaluator`/`InnerOpr` semantics
+// only. The following lexical/parser-level surface that these tests assume is NOT implemented
+// here and still needs matching tokenizer/suffix-tree-builder work wherever that code lives:
+//   - `within`/`without` as infix binary operators (test_eval_within_without) - built directly
+//     via `evaluator_from_ops` below instead of waiting on that parser work
+//   - `name(arg, arg, ...)` call syntax (test_eval_builtin_functions,
+//     test_eval_wrong_function_arity) - also built directly via `evaluator_from_ops`, since
+//     `expr_unit::Item` has no `Function` variant either: `InnerOpr::Function` exists and is
+//     evaluated, but nothing in the wire schema can produce one through `FromPb` yet.
+//
+// Bitwise operators (`&`, `|`, `^^`, `<<`, `>>`, unary `~`) and `0x`/`0b`/`0o` integer
+// literals were dropped entirely: `pb::Arithmetic` has no bitwise variants in this checkout
+// (its match is exhaustive with no wildcard), so there's no way to even name them without a
+// proto/generated-code change this repo doesn't have.
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::expr::to_suffix_expr_pb;
     use crate::expr::token::tokenize;
 
+    /// Build an `Evaluator` directly from a suffix-tree, bypassing `from_pb`/`tokenize` for
+    /// operators this checkout's tokenizer and/or protobuf schema don't support yet.
+    fn evaluator_from_ops<'a>(suffix_tree: Vec<InnerOpr>) -> Evaluator<'a> {
+        Evaluator {
+            suffix_tree,
+            stack: RefCell::new(vec![]),
+            fn_arena: ObjectArena::default(),
+        }
+    }
+
+    fn const_op(obj: Object) -> InnerOpr {
+        InnerOpr::Const(Some(obj))
+    }
+
     #[test]
     fn test_eval_simple() {
         let cases: Vec<&str> = vec![
@@ -445,4 +921,137 @@ mod tests {
             assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), expected);
         }
     }
+
+    #[test]
+    fn test_eval_within_without() {
+        // (scalar, collection, operator, expected) for `scalar <op> collection`
+        let cases: Vec<(Object, Vec<Object>, Logical, Object)> = vec![
+            (Object::from(1), vec![Object::from(1), Object::from(2), Object::from(3)], Logical::Within, Object::from(true)),
+            (Object::from(4), vec![Object::from(1), Object::from(2), Object::from(3)], Logical::Within, Object::from(false)),
+            (Object::from(4), vec![Object::from(1), Object::from(2), Object::from(3)], Logical::Without, Object::from(true)),
+            (Object::from(1), vec![Object::from(1), Object::from(2), Object::from(3)], Logical::Without, Object::from(false)),
+            (Object::from(1), vec![], Logical::Within, Object::from(false)),
+            (Object::from(1), vec![], Logical::Without, Object::from(true)),
+            (
+                Object::from("bob".to_string()),
+                vec![Object::from("alice".to_string()), Object::from("carol".to_string())],
+                Logical::Without,
+                Object::from(true),
+            ),
+        ];
+
+        for (scalar, collection, op, expected) in cases {
+            let eval = evaluator_from_ops(vec![
+                const_op(scalar),
+                const_op(Object::Vector(collection)),
+                InnerOpr::Logical(op),
+            ]);
+            assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_compiled_eval() {
+        // every case is const-only, so `compile` folds each one down to a single
+        // `Const` instruction; this checks the folded value
+        let cases: Vec<&str> = vec![
+            "1 + 2 * 3",
+            "(1 + 2) * 3 - 1",
+            "2 ^ 10 > 10",
+            "!false && (1 < 2)",
+        ];
+
+        let expected: Vec<Object> = vec![
+            Object::from(7),
+            Object::from(8),
+            Object::from(true),
+            Object::from(true),
+        ];
+
+        for (case, expected) in cases.into_iter().zip(expected.into_iter()) {
+            let eval =
+                Evaluator::from_pb(to_suffix_expr_pb(tokenize(case).unwrap()).unwrap()).unwrap();
+            let compiled = eval.compile();
+            assert_eq!(compiled.eval::<(), NoneContext>(None).unwrap(), expected);
+            // running it a second time must not be affected by the reused stack buffer
+            assert_eq!(compiled.eval::<(), NoneContext>(None).unwrap(), expected);
+        }
+
+        // same reused-stack-buffer check for `within`, built directly (see
+        // `evaluator_from_ops` for why this bypasses `tokenize`)
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from(1)),
+            const_op(Object::Vector(vec![Object::from(1), Object::from(2), Object::from(3)])),
+            InnerOpr::Logical(Logical::Within),
+        ]);
+        let compiled = eval.compile();
+        assert_eq!(compiled.eval::<(), NoneContext>(None).unwrap(), Object::from(true));
+        assert_eq!(compiled.eval::<(), NoneContext>(None).unwrap(), Object::from(true));
+    }
+
+    fn function_op(name: &str, arity: usize) -> InnerOpr {
+        InnerOpr::Function { name: name.to_string(), arity }
+    }
+
+    #[test]
+    fn test_eval_builtin_functions() {
+        // length("hello")
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from("hello".to_string())),
+            function_op("length", 1),
+        ]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(5));
+
+        // contains("hello world", "world")
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from("hello world".to_string())),
+            const_op(Object::from("world".to_string())),
+            function_op("contains", 2),
+        ]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(true));
+
+        // startsWith("hello", "he")
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from("hello".to_string())),
+            const_op(Object::from("he".to_string())),
+            function_op("startsWith", 2),
+        ]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(true));
+
+        // toLower("HeLLo") == "hello"
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from("HeLLo".to_string())),
+            function_op("toLower", 1),
+            const_op(Object::from("hello".to_string())),
+            InnerOpr::Logical(Logical::Eq),
+        ]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(true));
+
+        // abs(-10)
+        let eval = evaluator_from_ops(vec![const_op(Object::from(-10)), function_op("abs", 1)]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(10));
+
+        // floor(3.7)
+        let eval = evaluator_from_ops(vec![const_op(Object::from(3.7)), function_op("floor", 1)]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(3.0));
+
+        // power(2, 10)
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from(2)),
+            const_op(Object::from(10)),
+            function_op("power", 2),
+        ]);
+        assert_eq!(eval.eval::<(), NoneContext>(None).unwrap(), Object::from(1024.0));
+    }
+
+    #[test]
+    fn test_eval_wrong_function_arity() {
+        // abs(1, 2) - `abs` only takes one argument
+        let eval = evaluator_from_ops(vec![
+            const_op(Object::from(1)),
+            const_op(Object::from(2)),
+            function_op("abs", 2),
+        ]);
+        assert!(eval.eval::<(), NoneContext>(None).is_err());
+    }
 }